@@ -10,6 +10,9 @@ fn main() {
   let mut buffer: Vec<u8> = Vec::new();
   let _result = file.read_to_end(&mut buffer);
 
-  let klvs = parser::parse(&buffer);
+  match parser::parse(&buffer) {
+    Ok(klvs) => println!("parsed {} klvs", klvs.len()),
+    Err(e) => eprintln!("failed to parse klv stream: {}", e),
+  }
 }
 
@@ -79,8 +79,165 @@
 //! # [ST0601.8](http://www.gwg.nga.mil/misb/docs/standards/ST0601.8.pdf)
 //! # [Encoding to MXF](https://www.amwa.tv/downloads/whitepapers/encodingtoMXF.pdf)
 
+pub mod uas_lds_parser;
+
+use std::error::Error;
+use std::fmt;
+
+use uas_lds_parser::{Tlv, UAS_LOCAL_SET_KEY, parse_local_set};
+
 const KEY_LENGTH: usize = 16;
 
+/// Errors produced while walking a BER encoded byte stream.
+///
+/// Every variant carries the `offset` into the original input where the
+/// problem was detected, so a caller parsing a long-running stream can
+/// report exactly where things went wrong.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ParseError {
+  /// The input ended before a key, length, or value could be read in full.
+  UnexpectedEof { offset: usize },
+  /// A Universal Label key did not start with the expected `0x06` OID byte.
+  BadKeyPrefix { offset: usize, found: u8 },
+  /// The BER long-form length prefix declared more bytes than this parser
+  /// is able to decode into a length.
+  LengthTooLong { offset: usize, declared_bytes: usize },
+  /// The value portion of a KLV was shorter than its declared length.
+  TruncatedValue { offset: usize, need: usize, have: usize },
+  /// A nested set recursed past `MAX_NESTING_DEPTH`, which guards against
+  /// malformed, self-referential nesting.
+  NestingTooDeep { depth: usize },
+}
+
+impl fmt::Display for ParseError {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match *self {
+      ParseError::UnexpectedEof { offset } => {
+        write!(f, "unexpected end of input at offset {}", offset)
+      }
+      ParseError::BadKeyPrefix { offset, found } => {
+        write!(f, "expected key to start with 0x06 at offset {}, found {:#04x}", offset, found)
+      }
+      ParseError::LengthTooLong { offset, declared_bytes } => {
+        write!(f, "BER length at offset {} declared {} bytes, which is too long to decode", offset, declared_bytes)
+      }
+      ParseError::TruncatedValue { offset, need, have } => {
+        write!(f, "value at offset {} needs {} bytes but only {} are available", offset, need, have)
+      }
+      ParseError::NestingTooDeep { depth } => {
+        write!(f, "nested set recursion exceeded the depth limit ({} levels)", depth)
+      }
+    }
+  }
+}
+
+impl Error for ParseError {}
+
+/// Implemented by types that can be serialized back to their BER/KLV byte
+/// representation, so parsed packets can be round-tripped for test
+/// fixtures, transcoding, or validation.
+pub trait Codec {
+  /// The exact number of bytes `encode` will write.
+  fn byte_size(&self) -> usize;
+  /// Appends this value's encoded bytes to `out`.
+  fn encode(&self, out: &mut Vec<u8>);
+}
+
+/// The number of bytes a BER length prefix for `len` will take up.
+pub(crate) fn ber_length_size(len: usize) -> usize {
+  if len <= 127 {
+    1
+  } else {
+    1 + ber_length_byte_count(len)
+  }
+}
+
+fn ber_length_byte_count(len: usize) -> usize {
+  let mut count = 0;
+  let mut remaining = len;
+  while remaining > 0 {
+    count += 1;
+    remaining >>= 8;
+  }
+  if count == 0 {
+    1
+  } else {
+    count
+  }
+}
+
+/// Writes `len` as a BER length prefix: short form when `len <= 127`,
+/// otherwise the long form `0x80 | n` followed by `n` big-endian bytes.
+pub(crate) fn encode_ber_length(len: usize, out: &mut Vec<u8>) {
+  if len <= 127 {
+    out.push(len as u8);
+  } else {
+    let byte_count = ber_length_byte_count(len);
+    out.push(0x80 | byte_count as u8);
+    for i in (0..byte_count).rev() {
+      out.push(((len >> (8 * i)) & 0xFF) as u8);
+    }
+  }
+}
+
+/// Why [`decode_ber_length`] couldn't produce a length.
+pub(crate) enum LengthDecodeError {
+  /// `bytes` ended before the length prefix did. Buffered parsers treat
+  /// this as `ParseError::UnexpectedEof`; streaming parsers treat it as
+  /// `ParseStatus::Incomplete` and retry once more bytes arrive.
+  Insufficient,
+  /// The prefix itself is malformed (e.g. declares an undecodable length).
+  Parse(ParseError),
+}
+
+/// Decodes a single BER length prefix (short or long form) from the start
+/// of `bytes`. Returns the decoded length and the number of bytes the
+/// prefix itself took up, so callers can advance past it. `offset` is only
+/// used to annotate errors with the prefix's position in the original
+/// buffer.
+pub(crate) fn decode_ber_length(bytes: &[u8], offset: usize) -> Result<(usize, usize), LengthDecodeError> {
+  let indicator = *bytes.first().ok_or(LengthDecodeError::Insufficient)?;
+
+  if indicator < 128 {
+    return Ok((indicator as usize, 1));
+  }
+
+  let bytes_length: usize = (indicator & 0x7F) as usize;
+  if bytes_length == 0 || bytes_length > 8 {
+    return Err(LengthDecodeError::Parse(ParseError::LengthTooLong { offset, declared_bytes: bytes_length }));
+  }
+
+  let end = 1 + bytes_length;
+  if end > bytes.len() {
+    return Err(LengthDecodeError::Insufficient);
+  }
+
+  let num = ber_uint(&bytes[1..end]);
+  Ok((num as usize, end))
+}
+
+/// Decodes a single BER-OID encoded tag from the start of `bytes`: 7-bit
+/// groups, most significant first, with the high bit marking continuation.
+/// Returns the decoded tag and the number of bytes consumed. `offset` is
+/// only used to annotate the `UnexpectedEof` error with the tag's starting
+/// position.
+pub(crate) fn decode_ber_oid(bytes: &[u8], offset: usize) -> Result<(u64, usize), ParseError> {
+  let mut tag: u64 = 0;
+  let mut consumed = 0;
+
+  loop {
+    let byte = *bytes.get(consumed).ok_or(ParseError::UnexpectedEof { offset })?;
+    tag = (tag << 7) | u64::from(byte & 0x7F);
+    consumed += 1;
+
+    if byte & 0x80 == 0 {
+      break;
+    }
+  }
+
+  Ok((tag, consumed))
+}
+
 #[derive(Debug)]
 pub struct Klv {
   key: [u8; KEY_LENGTH],
@@ -88,227 +245,659 @@ pub struct Klv {
   value: Vec<u8>,
 }
 
+impl Klv {
+  /// If this `Klv`'s key is the UAS Datalink Local Set universal key, parses
+  /// its value as a set of tag/length/value elements. Returns `None` for any
+  /// other key.
+  pub fn as_uas_local_set(&self) -> Option<Result<Vec<Tlv>, ParseError>> {
+    if self.key == UAS_LOCAL_SET_KEY {
+      Some(parse_local_set(&self.value))
+    } else {
+      None
+    }
+  }
+}
+
+impl Codec for Klv {
+  fn byte_size(&self) -> usize {
+    KEY_LENGTH + ber_length_size(self.value.len()) + self.value.len()
+  }
+
+  fn encode(&self, out: &mut Vec<u8>) {
+    out.extend_from_slice(&self.key);
+    encode_ber_length(self.value.len(), out);
+    out.extend_from_slice(&self.value);
+  }
+}
+
+impl FieldValue for Klv {
+  fn raw_value(&self) -> &[u8] {
+    &self.value
+  }
+}
+
+/// Interprets a KLV/TLV value as one of the primitive encodings ST0601
+/// tags are defined in terms of: fixed-width big-endian integers, an
+/// IMAPB-scaled float, or UTF-8 text.
+pub trait FieldValue {
+  /// The raw value bytes to interpret.
+  fn raw_value(&self) -> &[u8];
+
+  fn as_u8(&self) -> Option<u8> {
+    match self.raw_value() {
+      [b] => Some(*b),
+      _ => None,
+    }
+  }
+
+  fn as_u16(&self) -> Option<u16> {
+    if self.raw_value().len() != 2 {
+      return None;
+    }
+    Some(ber_uint(self.raw_value()) as u16)
+  }
+
+  fn as_u32(&self) -> Option<u32> {
+    if self.raw_value().len() != 4 {
+      return None;
+    }
+    Some(ber_uint(self.raw_value()) as u32)
+  }
+
+  fn as_i16(&self) -> Option<i16> {
+    if self.raw_value().len() != 2 {
+      return None;
+    }
+    Some(ber_uint(self.raw_value()) as u16 as i16)
+  }
+
+  /// Decodes the value as an IMAPB-scaled float over `[min, max]`:
+  /// `min + raw * (max - min) / (2^(8*len) - 1)`.
+  fn as_imapb(&self, min: f64, max: f64) -> Option<f64> {
+    let bytes = self.raw_value();
+    if bytes.is_empty() || bytes.len() > 8 {
+      return None;
+    }
+
+    let raw = ber_uint(bytes) as f64;
+    let max_raw = 2f64.powi((8 * bytes.len()) as i32) - 1.0;
+    Some(min + raw * (max - min) / max_raw)
+  }
+
+  fn as_utf8(&self) -> Option<&str> {
+    std::str::from_utf8(self.raw_value()).ok()
+  }
+}
+
+/// How many more bytes a streaming [`Parser`] needs before it can make
+/// progress on the element currently being read.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Needed {
+  /// The exact number of additional bytes required.
+  Exact(usize),
+  /// Not even the BER length prefix has arrived yet, so the total can't be
+  /// computed until more bytes are fed in.
+  Unknown,
+}
+
+/// The result of a single streaming read attempt.
+#[derive(Debug)]
+pub enum ParseStatus {
+  /// A full `Klv` was available and the parser's cursor has advanced past it.
+  Complete(Klv),
+  /// Not enough bytes were buffered yet; the cursor was not advanced, so the
+  /// same read can be retried after more data is fed in.
+  Incomplete(Needed),
+}
+
+/// Describes how a set's keys are encoded, so the same `Parser` engine can
+/// drive both the outer 16-byte KLV stream and inner local or universal
+/// sets that use other MISB key conventions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyFormat {
+  /// A full 16-byte SMPTE Universal Label, as used by the outer KLV stream.
+  Universal16,
+  /// A fixed-width key of `n` bytes, e.g. the 1/2/4-byte keys used by
+  /// other MISB local sets.
+  Fixed(usize),
+  /// A BER-OID encoded tag of variable width, as used by the UAS Local Set.
+  BerOid,
+}
+
+/// Describes how a set's lengths are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LengthFormat {
+  /// Standard BER short/long form, as used throughout KLV/BER.
+  Ber,
+  /// A fixed-width length of `n` bytes.
+  Fixed(usize),
+}
+
+/// A decoded key or tag, shaped by whichever [`KeyFormat`] produced it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Key {
+  /// A full 16-byte Universal Label.
+  Universal([u8; KEY_LENGTH]),
+  /// A numeric tag, as decoded from a fixed-width or BER-OID key.
+  Tag(u64),
+}
+
 #[derive(Debug)]
-pub struct Parser<'a> {
-  input: &'a [u8],
-  klvs: Vec<Klv>,
+pub struct Parser {
+  input: Vec<u8>,
   cursor: usize,
+  key_format: KeyFormat,
+  length_format: LengthFormat,
 }
 
-impl<'a> Parser<'a> {
+impl Parser {
   pub fn new(input: &[u8]) -> Parser {
+    Parser::new_with_format(input, KeyFormat::Universal16, LengthFormat::Ber)
+  }
+
+  /// Builds a `Parser` driven by the given key and length formats, so the
+  /// same engine can walk inner local/universal sets that don't use the
+  /// outer KLV stream's 16-byte keys and BER lengths.
+  pub fn new_with_format(input: &[u8], key_format: KeyFormat, length_format: LengthFormat) -> Parser {
     Parser {
-      input: input,
-      klvs: Vec::new(),
+      input: input.to_vec(),
       cursor: 0,
+      key_format,
+      length_format,
     }
   }
 
+  /// Appends more bytes to the end of the buffer, for use as data arrives
+  /// from a socket or pipe. Does not affect the cursor, so a pending
+  /// [`ParseStatus::Incomplete`] read can simply be retried.
+  pub fn feed(&mut self, more: &[u8]) {
+    self.input.extend_from_slice(more);
+  }
+
   fn has_next(&self) -> bool {
     self.cursor < self.input.len()
   }
 
-  fn read_next(&mut self) -> Klv {
-    let key: [u8; KEY_LENGTH] = self.get_key().expect("Unable to parse key");
-    println!("key is: {:?}", key);
-    let length: usize = self.get_length().expect("Unable to parse length");
-    println!("length is: {}", length);
-    let value: Vec<u8> = self.get_value(length).expect("Unable to parse value");
+  /// Attempts to read the next `Klv` without assuming the whole stream is
+  /// buffered. Unlike `read_next`, running out of bytes is not an error:
+  /// it is reported as `ParseStatus::Incomplete` and the cursor is left
+  /// unmoved so the same read can be retried once more data is fed in.
+  pub fn try_read_next(&mut self) -> Result<ParseStatus, ParseError> {
+    let start = self.cursor;
+    let available = self.input.len() - start;
+
+    if available < KEY_LENGTH {
+      return Ok(ParseStatus::Incomplete(Needed::Exact(KEY_LENGTH - available)));
+    }
+
+    if self.input[start] != 0x06 {
+      return Err(ParseError::BadKeyPrefix { offset: start, found: self.input[start] });
+    }
+
+    let length_pos = start + KEY_LENGTH;
+    if length_pos >= self.input.len() {
+      return Ok(ParseStatus::Incomplete(Needed::Unknown));
+    }
 
-    let klv = Klv {
-      key: key,
-      length: length,
-      value: value,
+    let (length, consumed) = match decode_ber_length(&self.input[length_pos..], length_pos) {
+      Ok(result) => result,
+      Err(LengthDecodeError::Insufficient) => return Ok(ParseStatus::Incomplete(Needed::Unknown)),
+      Err(LengthDecodeError::Parse(e)) => return Err(e),
     };
+    let value_start = length_pos + consumed;
+
+    let value_end = value_start + length;
+    if value_end > self.input.len() {
+      return Ok(ParseStatus::Incomplete(Needed::Exact(value_end - self.input.len())));
+    }
+
+    let mut key = [0u8; KEY_LENGTH];
+    key.copy_from_slice(&self.input[start..start + KEY_LENGTH]);
+    let value = self.input[value_start..value_end].to_vec();
+
+    self.cursor = value_end;
+    self.compact();
+    Ok(ParseStatus::Complete(Klv { key, length, value }))
+  }
 
-    println!("klv: {:?}", klv);
-    klv
+  /// Drops bytes already consumed by a completed read so a long-lived
+  /// stream doesn't retain every byte it has ever been fed.
+  fn compact(&mut self) {
+    if self.cursor > 0 {
+      self.input.drain(..self.cursor);
+      self.cursor = 0;
+    }
+  }
+
+  fn read_next(&mut self) -> Result<Klv, ParseError> {
+    let key: [u8; KEY_LENGTH] = self.get_key()?;
+    let length: usize = self.get_length()?;
+    let value: Vec<u8> = self.get_value(length)?;
+
+    Ok(Klv {
+      key,
+      length,
+      value,
+    })
   }
 
   fn increment_cursor(&mut self, bytes: usize) {
     self.cursor += bytes;
   }
 
-  fn get_length(&mut self) -> Option<usize> {
-    if self.cursor < self.input.len() {
-      let byte = self.input[self.cursor];
-
-      // here we have to check for BER short or long form.
-      // short form will have a 0 as the MSB, so the value
-      // of this byte will be 127 or less.
-      if byte < 128 {
-        self.increment_cursor(1);
-        Some(byte as usize)
-      } else {
-        // we now know that the BER form is the long form.
-        // We now have to check the remaining bytes to see
-        // how many more bytes will contain the actual length
-        // of the value portion of this packet.
-        let bytes_length: usize = (byte - 127) as usize;
-        let u8s_array: &[u8] = &self.input[self.cursor..self.cursor + bytes_length];
-        self.increment_cursor(bytes_length);
-        let num: u32 = u8s_to_u32(u8s_array);
-        Some(num as usize)
-        }
-    } else {
-      None
+  fn get_length(&mut self) -> Result<usize, ParseError> {
+    match decode_ber_length(&self.input[self.cursor..], self.cursor) {
+      Ok((length, consumed)) => {
+        self.increment_cursor(consumed);
+        Ok(length)
+      }
+      Err(LengthDecodeError::Insufficient) => Err(ParseError::UnexpectedEof { offset: self.cursor }),
+      Err(LengthDecodeError::Parse(e)) => Err(e),
     }
   }
 
-  fn get_key(&mut self) -> Option<[u8; KEY_LENGTH]> {
+  fn get_key(&mut self) -> Result<[u8; KEY_LENGTH], ParseError> {
     let cursor_start_pos = self.cursor;
     let cursor_end_pos = self.cursor + KEY_LENGTH;
 
-    println!("cursor_start: {}, cursor_end: {}", cursor_start_pos, cursor_end_pos);
-    if self.cursor < self.input.len() && cursor_end_pos <= self.input.len() {
-      self.increment_cursor(KEY_LENGTH);
-      let input_slice: &[u8] = &self.input[cursor_start_pos..cursor_end_pos];
-      let mut klv_array = [0u8; KEY_LENGTH];
+    if cursor_end_pos > self.input.len() {
+      return Err(ParseError::UnexpectedEof { offset: cursor_start_pos });
+    }
 
-      for (&x, p) in input_slice.iter().zip(klv_array.iter_mut()) {
-        *p = x;
-      }
+    if self.input[cursor_start_pos] != 0x06 {
+      return Err(ParseError::BadKeyPrefix { offset: cursor_start_pos, found: self.input[cursor_start_pos] });
+    }
 
-      Some(klv_array)
-    } else {
-      None
+    self.increment_cursor(KEY_LENGTH);
+    let input_slice: &[u8] = &self.input[cursor_start_pos..cursor_end_pos];
+    let mut klv_array = [0u8; KEY_LENGTH];
+
+    for (&x, p) in input_slice.iter().zip(klv_array.iter_mut()) {
+      *p = x;
     }
+
+    Ok(klv_array)
   }
 
   /// get_value is a little tricky.  In this function we need to separate out all
   /// the bytes specified by the length argument into more key lentgh values, or
   /// tag length values in this case.
-  fn get_value(&mut self, length: usize) -> Option<Vec<u8>> {
+  fn get_value(&mut self, length: usize) -> Result<Vec<u8>, ParseError> {
     let cursor_start_pos = self.cursor;
     let cursor_end_pos = self.cursor + length;
 
-    println!("cursor_start: {}, cursor_end: {}", cursor_start_pos, cursor_end_pos);
-    if self.cursor < self.input.len() && cursor_end_pos <= self.input.len() {
-      self.increment_cursor(length);
-      let input_slice: &[u8] = &self.input[cursor_start_pos..cursor_end_pos];
-      Some(input_slice.to_vec())
-    } else {
-      None
+    if cursor_end_pos > self.input.len() {
+      return Err(ParseError::TruncatedValue {
+        offset: cursor_start_pos,
+        need: length,
+        have: self.input.len() - cursor_start_pos,
+      });
+    }
+
+    self.increment_cursor(length);
+    let input_slice: &[u8] = &self.input[cursor_start_pos..cursor_end_pos];
+    Ok(input_slice.to_vec())
+  }
+
+  /// Reads the next key according to this parser's `key_format`.
+  fn read_key(&mut self) -> Result<Key, ParseError> {
+    match self.key_format {
+      KeyFormat::Universal16 => self.get_key().map(Key::Universal),
+      KeyFormat::Fixed(n) => self.read_fixed_key(n),
+      KeyFormat::BerOid => self.read_ber_oid_key(),
     }
   }
+
+  fn read_fixed_key(&mut self, width: usize) -> Result<Key, ParseError> {
+    let start = self.cursor;
+    let end = start + width;
+    if end > self.input.len() {
+      return Err(ParseError::UnexpectedEof { offset: start });
+    }
+
+    let tag = ber_uint(&self.input[start..end]);
+    self.increment_cursor(width);
+    Ok(Key::Tag(tag))
+  }
+
+  fn read_ber_oid_key(&mut self) -> Result<Key, ParseError> {
+    let start = self.cursor;
+    let (tag, consumed) = decode_ber_oid(&self.input[start..], start)?;
+    self.increment_cursor(consumed);
+    Ok(Key::Tag(tag))
+  }
+
+  /// Reads the next length according to this parser's `length_format`.
+  fn read_length(&mut self) -> Result<usize, ParseError> {
+    match self.length_format {
+      LengthFormat::Ber => self.get_length(),
+      LengthFormat::Fixed(n) => {
+        let start = self.cursor;
+        let end = start + n;
+        if end > self.input.len() {
+          return Err(ParseError::UnexpectedEof { offset: start });
+        }
+
+        let length = ber_uint(&self.input[start..end]) as usize;
+        self.increment_cursor(n);
+        Ok(length)
+      }
+    }
+  }
+
+  /// Reads one (key, length, value) element using this parser's configured
+  /// key and length formats.
+  fn read_element(&mut self) -> Result<(Key, usize, Vec<u8>), ParseError> {
+    let key = self.read_key()?;
+    let length = self.read_length()?;
+    let value = self.get_value(length)?;
+    Ok((key, length, value))
+  }
 }
 
-pub fn parse(bytes: &[u8]) -> Vec<Klv> {
+/// A node in the tree produced by [`parse_nested`]: a key/length/value
+/// element, plus any children recovered by recursing into its value when
+/// the [`NestedSetRegistry`] says it is itself a nested set.
+#[derive(Debug)]
+pub struct Element {
+  pub key: Key,
+  pub length: usize,
+  pub value: Vec<u8>,
+  pub children: Vec<Element>,
+}
+
+/// Guards against malformed, self-referential nesting in [`parse_nested`].
+const MAX_NESTING_DEPTH: usize = 16;
+
+/// Maps a key/tag to the key and length format of the nested set it
+/// contains, so [`parse_nested`] knows which elements to recurse into.
+#[derive(Debug, Default)]
+pub struct NestedSetRegistry {
+  entries: Vec<(Key, KeyFormat, LengthFormat)>,
+}
+
+impl NestedSetRegistry {
+  pub fn new() -> NestedSetRegistry {
+    NestedSetRegistry { entries: Vec::new() }
+  }
+
+  /// Registers `key` as containing a nested set encoded with `key_format`
+  /// and `length_format`.
+  pub fn register(&mut self, key: Key, key_format: KeyFormat, length_format: LengthFormat) {
+    self.entries.push((key, key_format, length_format));
+  }
+
+  fn format_for(&self, key: &Key) -> Option<(KeyFormat, LengthFormat)> {
+    self.entries
+      .iter()
+      .find(|(registered_key, _, _)| registered_key == key)
+      .map(|(_, key_format, length_format)| (*key_format, *length_format))
+  }
+}
+
+/// Parses `bytes` into a tree of [`Element`]s, recursing into any element
+/// whose key is registered in `registry` as containing a nested set.
+///
+/// This is the entry point for formats like nested ST0601 local sets, or
+/// other MISB datasets that mix 1-, 2-, 4-byte, or BER-OID keys with
+/// universal-labeled sets. Recursion is capped at `MAX_NESTING_DEPTH` to
+/// guard against malformed, self-referential nesting.
+pub fn parse_nested(
+  bytes: &[u8],
+  key_format: KeyFormat,
+  length_format: LengthFormat,
+  registry: &NestedSetRegistry,
+) -> Result<Vec<Element>, ParseError> {
+  parse_nested_at_depth(bytes, key_format, length_format, registry, 0)
+}
+
+fn parse_nested_at_depth(
+  bytes: &[u8],
+  key_format: KeyFormat,
+  length_format: LengthFormat,
+  registry: &NestedSetRegistry,
+  depth: usize,
+) -> Result<Vec<Element>, ParseError> {
+  if depth > MAX_NESTING_DEPTH {
+    return Err(ParseError::NestingTooDeep { depth });
+  }
+
+  let mut parser = Parser::new_with_format(bytes, key_format, length_format);
+  let mut elements: Vec<Element> = Vec::new();
+
+  while parser.has_next() {
+    let (key, length, value) = parser.read_element()?;
+
+    let children = match registry.format_for(&key) {
+      Some((child_key_format, child_length_format)) => {
+        parse_nested_at_depth(&value, child_key_format, child_length_format, registry, depth + 1)?
+      }
+      None => Vec::new(),
+    };
+
+    elements.push(Element { key, length, value, children });
+  }
+
+  Ok(elements)
+}
+
+pub fn parse(bytes: &[u8]) -> Result<Vec<Klv>, ParseError> {
   let mut klv_vec: Vec<Klv> = Vec::new();
   let mut parser: Parser = Parser::new(bytes);
   while parser.has_next() {
-    let klv = parser.read_next();
+    let klv = parser.read_next()?;
     klv_vec.push(klv);
   }
-  klv_vec
+  Ok(klv_vec)
+}
+
+/// Incrementally parses `Klv`s as bytes arrive, for use over a socket or
+/// pipe where the whole stream isn't available up front.
+///
+/// Call [`KlvStream::feed`] as new bytes come in, then drain the iterator:
+/// each call to `next()` yields a completed `Klv` if one is ready, or
+/// `None` once the buffered bytes run out. `None` does not mean the
+/// stream is finished - feed more bytes and iterate again.
+///
+/// Once `next()` yields `Err`, the stream is fused: the malformed bytes
+/// are never re-parsed, and every subsequent call returns `None`.
+pub struct KlvStream {
+  parser: Parser,
+  failed: bool,
 }
 
-pub fn u8s_to_u32(bytes: &[u8]) -> u32 {
-  let size: usize = bytes.len();
-  match size {
-    2 => two_u8s_to_u32(bytes),
-    3 => three_u8s_to_u32(bytes),
-    4 => four_u8s_to_u32(bytes),
-    _ => panic!(format!("bytes array size must be between 2 and 4.  size was {}", size))
+impl KlvStream {
+  pub fn new() -> KlvStream {
+    KlvStream { parser: Parser::new(&[]), failed: false }
+  }
+
+  pub fn feed(&mut self, more: &[u8]) {
+    self.parser.feed(more);
   }
 }
 
-/// Takes the first two bytes (u8) from the slice
-/// and converts them to one u32 value.
-pub fn two_u8s_to_u32(bytes: &[u8]) -> u32 {
-  if bytes.len() >= 2 {
-    (u32::from(bytes[1]) << 8) | (u32::from(bytes[0]))
-  } else {
-    panic!("bytes array was too small to convert to u32.  Needed at least two elements in the bytes array");
+impl Default for KlvStream {
+  fn default() -> KlvStream {
+    KlvStream::new()
   }
 }
 
-pub fn three_u8s_to_u32(bytes: &[u8]) -> u32 {
-  if bytes.len() >= 3 {
-    (u32::from(bytes[2]) << 16) | (u32::from(bytes[1]) << 8) | (u32::from(bytes[0]))
-  } else {
-    panic!("bytes array was too small to convert to u32.  Needed at least three elements in the bytes array");
+impl Iterator for KlvStream {
+  type Item = Result<Klv, ParseError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.failed {
+      return None;
+    }
+
+    match self.parser.try_read_next() {
+      Ok(ParseStatus::Complete(klv)) => Some(Ok(klv)),
+      Ok(ParseStatus::Incomplete(_)) => None,
+      Err(e) => {
+        self.failed = true;
+        Some(Err(e))
+      }
+    }
   }
 }
 
-pub fn four_u8s_to_u32(bytes: &[u8]) -> u32 {
-  if bytes.len() >= 4 {
-    (
-      (u32::from(bytes[3]) << 24) |
-      (u32::from(bytes[2]) << 16) |
-      (u32::from(bytes[1]) << 8) |
-      (u32::from(bytes[0]))
-    )
-  } else {
-    panic!("bytes array was too small to convert to u32.  Needed at least four elements in the bytes array");
+/// Folds a big-endian byte slice into a `u64`, the way SMPTE KLV and BER
+/// encode both length prefixes and integer values. Supports 1-8 byte
+/// slices; the spec allows up to 9, but that exceeds what a `u64` can hold.
+pub fn ber_uint(bytes: &[u8]) -> u64 {
+  let mut acc: u64 = 0;
+  for &byte in bytes {
+    acc = (acc << 8) | u64::from(byte);
   }
+  acc
 }
 
 #[test]
 fn test_parser() {
   let data = include_bytes!("../../test/assets/out.klv");
-  let klvs: Vec<Klv> = parse(&data.to_vec());
+  let klvs: Vec<Klv> = parse(&data.to_vec()).expect("test fixture should parse cleanly");
 
   println!("{:?}", klvs);
 }
 
 #[test]
-fn test_two_bytes_to_u32() {
-  //                       8          7         6         5         4        3        2        1
-  // -------------------------------------------------------------------------------------------
-  // 16 bytes:         32768      16384      8192      4096      2048     1024      512      256
-  // 124:                  0          1         1         1         1        1        0        0
-  //
-  // 8 bytes:            128         64        32        16         8        4        2        1
-  // 45:                   0          0         1         0         1        1        0        1
-  let expected_num: u32 = 31789;
+fn test_round_trip_encoding() {
+  let data = include_bytes!("../../test/assets/out.klv");
+  let klvs: Vec<Klv> = parse(&data.to_vec()).expect("test fixture should parse cleanly");
 
-  let array: [u8; 2] = [45, 124];
+  let mut out: Vec<u8> = Vec::new();
+  for klv in &klvs {
+    klv.encode(&mut out);
+  }
 
-  let actual_num: u32 = two_u8s_to_u32(&array);
+  assert_eq!(out.as_slice(), &data[..]);
+}
+
+#[test]
+fn test_ber_uint_two_bytes() {
+  // big-endian: 45 is the most significant byte, 124 the least.
+  let expected_num: u64 = 11644;
 
-  assert_eq!(actual_num, expected_num);
+  let array: [u8; 2] = [45, 124];
+
+  assert_eq!(ber_uint(&array), expected_num);
 }
 
 #[test]
-fn test_three_bytes_to_u32() {
-  //                       8          7         6         5         4        3        2        1
-  // -------------------------------------------------------------------------------------------
-  // 24 bytes:       8388608    4194304   2097152   1048576    524288   262144   131072    65536
-  // 101:                  0          1         1         0         0        1        0        1
-  //
-  // 16 bytes:         32768      16384      8192      4096      2048     1024      512      256
-  // 124:                  0          1         1         1         1        1        0        0
-  //
-  // 8 bytes:            128         64        32        16         8        4        2        1
-  // 45:                   0          0         1         0         1        1        0        1
-  let expected_num: u32 = 6650925;
+fn test_ber_uint_three_bytes() {
+  let expected_num: u64 = 2980965;
 
   let array: [u8; 3] = [45, 124, 101];
-  let actual_num: u32 = three_u8s_to_u32(&array);
 
-  assert_eq!(actual_num, expected_num);
+  assert_eq!(ber_uint(&array), expected_num);
 }
 
 #[test]
-fn test_four_bytes_to_u32() {
-  //                       8          7         6         5         4        3        2        1
-  // -------------------------------------------------------------------------------------------
-  // 32 bytes:    2147483648 1073741824 536870912 268435456 134217728 67108864 33554432 16777216
-  // 12:                   0          0         0         0         1        1        0        0
-  //
-  // 24 bytes:       8388608    4194304   2097152   1048576    524288   262144   131072    65536
-  // 101:                  0          1         1         0         0        1        0        1
-  //
-  // 16 bytes:         32768      16384      8192      4096      2048     1024      512      256
-  // 124:                  0          1         1         1         1        1        0        0
-  //
-  // 8 bytes:            128         64        32        16         8        4        2        1
-  // 45:                   0          0         1         0         1        1        0        1
-  let expected_num: u32 = 207977517;
+fn test_ber_uint_four_bytes() {
+  let expected_num: u64 = 763127052;
 
   let array: [u8; 4] = [45, 124, 101, 12];
-  let actual_num: u32 = four_u8s_to_u32(&array);
 
-  assert_eq!(actual_num, expected_num);
+  assert_eq!(ber_uint(&array), expected_num);
+}
+
+#[test]
+fn test_klv_typed_value_accessors() {
+  let klv = Klv { key: [0u8; KEY_LENGTH], length: 2, value: vec![0x01, 0x2C] };
+
+  assert_eq!(klv.as_u16(), Some(0x012C));
+  assert_eq!(klv.as_i16(), Some(0x012C));
+  assert_eq!(klv.as_u8(), None);
+
+  // full-scale raw value should map to the top of the [min, max] range.
+  let full_scale = Klv { key: [0u8; KEY_LENGTH], length: 2, value: vec![0xFF, 0xFF] };
+  assert_eq!(full_scale.as_imapb(-1.0, 1.0), Some(1.0));
+
+  let text = Klv { key: [0u8; KEY_LENGTH], length: 5, value: b"hello".to_vec() };
+  assert_eq!(text.as_utf8(), Some("hello"));
+}
+
+#[test]
+fn test_parse_nested_recurses_into_registered_sets() {
+  // outer set: 1-byte tag 0x01 whose value is itself a nested set (tag 0x09,
+  // length 1, value 0x2A), plus a 1-byte tag 0x02 that is left as a leaf.
+  let bytes = [0x01, 0x03, 0x09, 0x01, 0x2A, 0x02, 0x01, 0x05];
+
+  let mut registry = NestedSetRegistry::new();
+  registry.register(Key::Tag(0x01), KeyFormat::Fixed(1), LengthFormat::Fixed(1));
+
+  let elements = parse_nested(&bytes, KeyFormat::Fixed(1), LengthFormat::Fixed(1), &registry)
+    .expect("bytes should parse");
+
+  assert_eq!(elements.len(), 2);
+
+  assert_eq!(elements[0].key, Key::Tag(0x01));
+  assert_eq!(elements[0].children.len(), 1);
+  assert_eq!(elements[0].children[0].key, Key::Tag(0x09));
+  assert_eq!(elements[0].children[0].value, vec![0x2A]);
+
+  assert_eq!(elements[1].key, Key::Tag(0x02));
+  assert!(elements[1].children.is_empty());
+}
+
+#[test]
+fn test_parse_nested_rejects_excessive_depth() {
+  // Build a chain of tag-0x01 elements each wrapping the next, nested far
+  // past MAX_NESTING_DEPTH, to confirm the guard stops it rather than
+  // recursing until the stack overflows.
+  let mut bytes: Vec<u8> = vec![0x02, 0x00]; // innermost leaf: tag 0x02, length 0
+  for _ in 0..32 {
+    let mut wrapped: Vec<u8> = vec![0x01, bytes.len() as u8];
+    wrapped.extend_from_slice(&bytes);
+    bytes = wrapped;
+  }
+
+  let mut registry = NestedSetRegistry::new();
+  registry.register(Key::Tag(0x01), KeyFormat::Fixed(1), LengthFormat::Fixed(1));
+
+  let result = parse_nested(&bytes, KeyFormat::Fixed(1), LengthFormat::Fixed(1), &registry);
+
+  match result {
+    Err(ParseError::NestingTooDeep { .. }) => {}
+    other => panic!("expected NestingTooDeep, got {:?}", other),
+  }
+}
+
+#[test]
+fn test_streaming_reports_needed_and_resumes() {
+  let mut key = [0u8; KEY_LENGTH];
+  key[0] = 0x06;
+
+  let mut stream = KlvStream::new();
+  stream.feed(&key);
+  assert!(stream.next().is_none(), "key with no length byte yet should be incomplete");
+
+  stream.feed(&[0x02]);
+  assert!(stream.next().is_none(), "length with no value bytes yet should be incomplete");
+
+  stream.feed(&[0xAB, 0xCD]);
+  match stream.next() {
+    Some(Ok(klv)) => assert_eq!(klv.value, vec![0xAB, 0xCD]),
+    other => panic!("expected a complete klv, got {:?}", other),
+  }
+
+  assert!(stream.next().is_none(), "no more bytes buffered");
+}
+
+#[test]
+fn test_streaming_compacts_consumed_bytes() {
+  let mut key = [0u8; KEY_LENGTH];
+  key[0] = 0x06;
+
+  let mut parser = Parser::new(&[]);
+  parser.feed(&key);
+  parser.feed(&[0x02, 0xAB, 0xCD]);
+
+  match parser.try_read_next() {
+    Ok(ParseStatus::Complete(_)) => {}
+    other => panic!("expected a complete klv, got {:?}", other),
+  }
+
+  assert_eq!(parser.cursor, 0, "consumed bytes should be drained, not just skipped over");
+  assert!(parser.input.is_empty(), "buffer should not retain bytes already read");
 }
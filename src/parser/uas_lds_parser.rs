@@ -40,8 +40,167 @@
 //! # [ST0601.8](http://www.gwg.nga.mil/misb/docs/standards/ST0601.8.pdf)
 //! # [EG0104.4](http://www.gwg.nga.mil/misb/docs/eg/EG0104.4.pdf)
 
+use super::{ber_length_size, decode_ber_length, decode_ber_oid, encode_ber_length, Codec, FieldValue, LengthDecodeError, ParseError};
+
+/// The 16-byte Universal Label identifying a UAS Datalink Local Set KLV.
+pub const UAS_LOCAL_SET_KEY: [u8; 16] = [
+  0x06, 0x0E, 0x2B, 0x34, 0x02, 0x0B, 0x01, 0x01,
+  0x0E, 0x01, 0x03, 0x01, 0x01, 0x00, 0x00, 0x00,
+];
+
+#[derive(Debug)]
 pub struct Tlv {
-  tag: u8,
-  length: u8,
+  tag: u64,
+  length: usize,
   value: Vec<u8>
+}
+
+/// Parses the value portion of a UAS Local Set KLV into its tag/length/value
+/// elements.
+///
+/// Each element's tag is BER-OID encoded: bytes with the high bit (`0x80`)
+/// set are folded into the tag as `tag = (tag << 7) | (byte & 0x7F)`, and the
+/// first byte with the high bit clear ends the tag. The length that follows
+/// uses the same BER short/long form as the outer KLV stream.
+pub fn parse_local_set(value: &[u8]) -> Result<Vec<Tlv>, ParseError> {
+  let mut tlvs: Vec<Tlv> = Vec::new();
+  let mut cursor: usize = 0;
+
+  while cursor < value.len() {
+    let tag_start = cursor;
+    let (tag, tag_consumed) = decode_ber_oid(&value[tag_start..], tag_start)?;
+    cursor += tag_consumed;
+
+    let length_pos = cursor;
+    let (length, length_consumed) = match decode_ber_length(&value[length_pos..], length_pos) {
+      Ok(result) => result,
+      Err(LengthDecodeError::Insufficient) => return Err(ParseError::UnexpectedEof { offset: length_pos }),
+      Err(LengthDecodeError::Parse(e)) => return Err(e),
+    };
+    let value_start = length_pos + length_consumed;
+
+    let value_end = value_start + length;
+    if value_end > value.len() {
+      return Err(ParseError::TruncatedValue {
+        offset: value_start,
+        need: length,
+        have: value.len() - value_start,
+      });
+    }
+
+    tlvs.push(Tlv {
+      tag,
+      length,
+      value: value[value_start..value_end].to_vec(),
+    });
+
+    cursor = value_end;
+  }
+
+  Ok(tlvs)
+}
+
+fn ber_oid_size(tag: u64) -> usize {
+  let mut size = 1;
+  let mut remaining = tag >> 7;
+  while remaining > 0 {
+    size += 1;
+    remaining >>= 7;
+  }
+  size
+}
+
+/// Writes `tag` as a BER-OID: the tag is split into 7-bit groups, most
+/// significant first, with the high bit set on every group but the last.
+fn encode_ber_oid(tag: u64, out: &mut Vec<u8>) {
+  let mut groups: Vec<u8> = Vec::new();
+  let mut remaining = tag;
+  loop {
+    groups.push((remaining & 0x7F) as u8);
+    remaining >>= 7;
+    if remaining == 0 {
+      break;
+    }
+  }
+
+  let last = groups.len() - 1;
+  for (i, group) in groups.iter().rev().enumerate() {
+    if i == last {
+      out.push(*group);
+    } else {
+      out.push(group | 0x80);
+    }
+  }
+}
+
+impl Codec for Tlv {
+  fn byte_size(&self) -> usize {
+    ber_oid_size(self.tag) + ber_length_size(self.value.len()) + self.value.len()
+  }
+
+  fn encode(&self, out: &mut Vec<u8>) {
+    encode_ber_oid(self.tag, out);
+    encode_ber_length(self.value.len(), out);
+    out.extend_from_slice(&self.value);
+  }
+}
+
+impl FieldValue for Tlv {
+  fn raw_value(&self) -> &[u8] {
+    &self.value
+  }
+}
+
+#[test]
+fn test_parse_local_set_single_byte_tags() {
+  // tag 2, length 1, value 0x4A; tag 5, length 2, value 0x00 0x64
+  let value = [0x02, 0x01, 0x4A, 0x05, 0x02, 0x00, 0x64];
+
+  let tlvs = parse_local_set(&value).expect("value should parse");
+
+  assert_eq!(tlvs.len(), 2);
+  assert_eq!(tlvs[0].tag, 2);
+  assert_eq!(tlvs[0].value, vec![0x4A]);
+  assert_eq!(tlvs[1].tag, 5);
+  assert_eq!(tlvs[1].value, vec![0x00, 0x64]);
+}
+
+#[test]
+fn test_parse_local_set_multi_byte_ber_oid_tag() {
+  // tag bytes 0x81 0x22 decode to (0x01 << 7) | 0x22 = 0xA2
+  let value = [0x81, 0x22, 0x01, 0x09];
+
+  let tlvs = parse_local_set(&value).expect("value should parse");
+
+  assert_eq!(tlvs.len(), 1);
+  assert_eq!(tlvs[0].tag, 0xA2);
+  assert_eq!(tlvs[0].value, vec![0x09]);
+}
+
+#[test]
+fn test_parse_local_set_long_form_length() {
+  // tag 1, BER long-form length 200 (0x81 0xC8), followed by 200 bytes of value.
+  let mut value = vec![0x01, 0x81, 0xC8];
+  value.extend(std::iter::repeat(0xAB).take(200));
+
+  let tlvs = parse_local_set(&value).expect("value should parse");
+
+  assert_eq!(tlvs.len(), 1);
+  assert_eq!(tlvs[0].tag, 1);
+  assert_eq!(tlvs[0].value.len(), 200);
+  assert!(tlvs[0].value.iter().all(|&b| b == 0xAB));
+}
+
+#[test]
+fn test_parse_local_set_round_trip() {
+  let value = [0x02, 0x01, 0x4A, 0x81, 0x22, 0x01, 0x09];
+
+  let tlvs = parse_local_set(&value).expect("value should parse");
+
+  let mut out: Vec<u8> = Vec::new();
+  for tlv in &tlvs {
+    tlv.encode(&mut out);
+  }
+
+  assert_eq!(out.as_slice(), &value[..]);
 }
\ No newline at end of file